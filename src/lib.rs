@@ -1,113 +1,626 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
 use std::sync::{Arc, Mutex, Weak};
 
+/// Errors produced by fallible graph operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    /// The handle's slot has never been allocated in this graph.
+    InvalidNode,
+    /// The handle's slot was allocated but the node has since been removed
+    /// (and its slot may already have been reused by a newer node).
+    NodeDead,
+}
+
+/// A generational handle to a node in the graph.
+///
+/// Node slots are reused after removal, so a plain index can't tell a live
+/// node apart from whatever used to occupy its slot. `generation` makes
+/// that distinction: a handle is only valid for the occupant that was
+/// present when it was issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    slot: usize,
+    generation: u64,
+}
+
+/// Sentinel stored in a free slot that terminates the free list.
+const FREE_TAIL: usize = usize::MAX;
+
+/// A graph slot: the live node, if any, plus a second field that is only
+/// meaningful while the slot is free, holding the index of the next free slot.
+type Slot<T, E> = (Option<Arc<Mutex<Node<T, E>>>>, usize);
+
+/// An edge to a neighboring node, carrying the weight attached to it.
+struct Edge<T, E> {
+    target: Weak<Mutex<Node<T, E>>>,
+    weight: E,
+}
+
 /// A node in the graph, containing a value and a list of neighbors.
-pub struct Node<T> {
+pub struct Node<T, E = ()> {
     value: T,
-    neighbors: Mutex<Vec<Weak<Mutex<Node<T>>>>>,
+    neighbors: Mutex<Vec<Edge<T, E>>>,
 }
 
 /// The graph structure, containing a list of nodes.
-pub struct Graph<T> {
-    nodes: Vec<Arc<Mutex<Node<T>>>>,
+///
+/// Edges carry a weight of type `E`, defaulting to `()` for unweighted
+/// graphs. `K` is the key type used to look up nodes by
+/// [`get_or_insert_node`](Graph::get_or_insert_node) /
+/// [`node_for_key`](Graph::node_for_key), defaulting to `T` itself.
+pub struct Graph<T, E = (), K = T> {
+    /// Each slot holds the live node, if any, alongside a second field that
+    /// is only meaningful while the slot is free: the index of the next
+    /// free slot (or `FREE_TAIL` if it is the last one).
+    nodes: Vec<Slot<T, E>>,
+    /// Generation counter per slot, bumped every time a node is removed.
+    generations: Vec<u64>,
+    /// Head of the free-slot list, or `None` if there are no free slots.
+    next_free: Option<usize>,
+    /// Index from a caller-chosen key to the node it identifies, so repeated
+    /// lookups don't need to scan `nodes`.
+    indices: HashMap<K, NodeId>,
+}
+
+impl<T, E, K> Default for Graph<T, E, K> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<T> Graph<T> {
+impl<T, E, K> Graph<T, E, K> {
     /// Creates a new, empty graph.
     pub fn new() -> Self {
-        Graph { nodes: Vec::new() }
+        Graph {
+            nodes: Vec::new(),
+            generations: Vec::new(),
+            next_free: None,
+            indices: HashMap::new(),
+        }
     }
 
     /// Adds a node with the given value to the graph.
-    /// Returns the index of the new node.
-    pub fn add_node(&mut self, value: T) -> usize {
+    /// Returns a handle identifying the new node.
+    pub fn add_node(&mut self, value: T) -> NodeId {
         let node = Arc::new(Mutex::new(Node {
             value,
             neighbors: Mutex::new(Vec::new()),
         }));
-        self.nodes.push(node);
-        self.nodes.len() - 1 // Return the index of the new node
+        let slot = if let Some(free) = self.next_free {
+            self.next_free = match self.nodes[free].1 {
+                FREE_TAIL => None,
+                next => Some(next),
+            };
+            self.nodes[free] = (Some(node), 0);
+            free
+        } else {
+            self.nodes.push((Some(node), 0));
+            self.generations.push(0);
+            self.nodes.len() - 1
+        };
+        NodeId {
+            slot,
+            generation: self.generations[slot],
+        }
     }
 
-    /// Adds an edge from the node at `from_index` to the node at `to_index`.
-    pub fn add_edge(&self, from_index: usize, to_index: usize) {
-        if from_index >= self.nodes.len() || to_index >= self.nodes.len() {
-            panic!("Node index out of bounds");
-        }
-        let from_node = &self.nodes[from_index];
-        let to_node = &self.nodes[to_index];
+    /// Removes the node identified by `id`, returning its value.
+    ///
+    /// The slot is pushed onto the free list and its generation is bumped,
+    /// so any other handle still pointing at it will be rejected with
+    /// `GraphError::NodeDead`. Any key registered for this node via
+    /// [`get_or_insert_node`](Graph::get_or_insert_node) is dropped too, so
+    /// a later lookup by that key allocates a fresh node instead of handing
+    /// back the now-dead handle.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<T, GraphError> {
+        self.resolve(id)?;
+        self.indices.retain(|_, &mut existing| existing != id);
+        let next = self.next_free.unwrap_or(FREE_TAIL);
+        let (slot_value, _) = std::mem::replace(&mut self.nodes[id.slot], (None, next));
+        self.next_free = Some(id.slot);
+        self.generations[id.slot] += 1;
+        let arc = slot_value.expect("slot was just validated as occupied");
+        let mutex = Arc::try_unwrap(arc)
+            .unwrap_or_else(|_| panic!("removed node still has outstanding references"));
+        Ok(mutex.into_inner().unwrap().value)
+    }
+
+    /// Adds an edge from the node at `from` to the node at `to`, carrying `weight`.
+    pub fn add_edge_weighted(&self, from: NodeId, to: NodeId, weight: E) -> Result<(), GraphError> {
+        let from_node = self.resolve(from)?;
+        let to_node = self.resolve(to)?;
 
         // Lock the mutex to access neighbors
         let binding = from_node.lock().unwrap();
         let mut from_node_neighbors = binding.neighbors.lock().unwrap();
-        from_node_neighbors.push(Arc::downgrade(to_node));
+        from_node_neighbors.push(Edge {
+            target: Arc::downgrade(to_node),
+            weight,
+        });
+        Ok(())
     }
 
-    /// Returns a clone of the value of the node at the given index.
-    pub fn get_node_value(&self, index: usize) -> Option<T>
+    /// Returns a clone of the value of the node identified by `id`.
+    pub fn get_node_value(&self, id: NodeId) -> Result<T, GraphError>
     where
         T: Clone,
     {
-        self.nodes.get(index).map(|node| node.lock().unwrap().value.clone())
+        self.resolve(id)
+            .map(|node| node.lock().unwrap().value.clone())
     }
 
-    /// Returns a vector of clones of the values of the neighbors of the node at the given index.
-    pub fn neighbors_of(&self, index: usize) -> Option<Vec<T>>
+    /// Returns, for each live neighbor of the node identified by `id`, a
+    /// clone of its value paired with the weight of the edge to it.
+    pub fn weighted_neighbors_of(&self, id: NodeId) -> Result<Vec<(T, E)>, GraphError>
     where
         T: Clone,
+        E: Clone,
     {
-        if index >= self.nodes.len() {
-            return None;
-        }
-        let node = &self.nodes[index];
+        let node = self.resolve(id)?;
         let node_guard = node.lock().unwrap();
         let neighbors = node_guard.neighbors.lock().unwrap();
-        let neighbor_values = neighbors
+        Ok(neighbors
             .iter()
-            .filter_map(|weak_neighbor| weak_neighbor.upgrade())
-            .map(|neighbor_arc| neighbor_arc.lock().unwrap().value.clone())
-            .collect();
-        Some(neighbor_values)
+            .filter_map(|edge| {
+                edge.target
+                    .upgrade()
+                    .map(|arc| (arc.lock().unwrap().value.clone(), edge.weight.clone()))
+            })
+            .collect())
+    }
+
+    /// Validates `id` against the current slot/generation state and
+    /// returns the live node it refers to.
+    fn resolve(&self, id: NodeId) -> Result<&Arc<Mutex<Node<T, E>>>, GraphError> {
+        let generation = *self.generations.get(id.slot).ok_or(GraphError::InvalidNode)?;
+        if generation != id.generation {
+            return Err(GraphError::NodeDead);
+        }
+        self.nodes[id.slot].0.as_ref().ok_or(GraphError::NodeDead)
+    }
+
+    /// Returns every occupied slot's index and node, in slot order.
+    fn live_nodes(&self) -> Vec<LiveNode<T, E>> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, (node, _))| node.as_ref().map(|arc| (slot, arc.clone())))
+            .collect()
+    }
+
+    /// Returns the handle previously registered for `key` by
+    /// [`get_or_insert_node`](Graph::get_or_insert_node), if any.
+    pub fn node_for_key(&self, key: &K) -> Option<NodeId>
+    where
+        K: Eq + Hash,
+    {
+        self.indices.get(key).copied()
+    }
+
+    /// Returns the node for `key`, inserting one with `value` if `key`
+    /// hasn't been seen before.
+    pub fn get_or_insert_node(&mut self, key: K, value: T) -> NodeId
+    where
+        K: Eq + Hash + Clone,
+    {
+        if let Some(&id) = self.indices.get(&key) {
+            return id;
+        }
+        let id = self.add_node(value);
+        self.indices.insert(key, id);
+        id
+    }
+}
+
+impl<T, K> Graph<T, (), K> {
+    /// Adds an unweighted edge from the node at `from` to the node at `to`.
+    pub fn add_edge(&self, from: NodeId, to: NodeId) -> Result<(), GraphError> {
+        self.add_edge_weighted(from, to, ())
+    }
+
+    /// Returns a vector of clones of the values of the neighbors of the node identified by `id`.
+    pub fn neighbors_of(&self, id: NodeId) -> Result<Vec<T>, GraphError>
+    where
+        T: Clone,
+    {
+        Ok(self
+            .weighted_neighbors_of(id)?
+            .into_iter()
+            .map(|(value, ())| value)
+            .collect())
+    }
+}
+
+impl<T, E, K> Graph<T, E, K> {
+    /// Renders the graph in Graphviz DOT format, labeling each node with its
+    /// `Display` representation.
+    pub fn to_dot(&self) -> String
+    where
+        T: std::fmt::Display,
+    {
+        self.to_dot_with(|value| value.to_string())
+    }
+
+    /// Renders the graph in Graphviz DOT format, labeling each node with
+    /// `labeler(value)`. Use this when `T` doesn't implement `Display`.
+    pub fn to_dot_with(&self, labeler: impl Fn(&T) -> String) -> String {
+        let mut out = String::new();
+        self.write_dot_with(&mut out, labeler)
+            .expect("writing DOT to a String never fails");
+        out
+    }
+
+    /// Writes the graph in Graphviz DOT format to `w`, labeling each node
+    /// with its `Display` representation.
+    pub fn write_dot<W: std::fmt::Write>(&self, w: &mut W) -> std::fmt::Result
+    where
+        T: std::fmt::Display,
+    {
+        self.write_dot_with(w, |value| value.to_string())
     }
+
+    /// Writes the graph in Graphviz DOT format to `w`, labeling each node
+    /// with `labeler(value)`. Use this when `T` doesn't implement `Display`.
+    pub fn write_dot_with<W: std::fmt::Write>(
+        &self,
+        w: &mut W,
+        labeler: impl Fn(&T) -> String,
+    ) -> std::fmt::Result {
+        let live = self.live_nodes();
+
+        writeln!(w, "digraph {{")?;
+        for (slot, node) in &live {
+            let label = labeler(&node.lock().unwrap().value);
+            writeln!(w, "    {} [label=\"{}\"];", slot, escape_dot_label(&label))?;
+        }
+
+        let mut seen_edges = HashSet::new();
+        for (from_slot, node) in &live {
+            let node_guard = node.lock().unwrap();
+            let neighbors = node_guard.neighbors.lock().unwrap();
+            for edge in neighbors.iter() {
+                let Some(target) = edge.target.upgrade() else {
+                    continue;
+                };
+                let Some((to_slot, _)) = live.iter().find(|(_, arc)| Arc::ptr_eq(arc, &target)) else {
+                    continue;
+                };
+                if seen_edges.insert((*from_slot, *to_slot)) {
+                    writeln!(w, "    {} -> {};", from_slot, to_slot)?;
+                }
+            }
+        }
+        writeln!(w, "}}")
+    }
+}
+
+/// Escapes `"` and `\` so `label` is safe to embed in a DOT quoted string.
+fn escape_dot_label(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
-impl<T> Drop for Graph<T> {
+impl<T, E, K> Drop for Graph<T, E, K> {
     fn drop(&mut self) {
-        // Clear the nodes vector to drop all Arc<Node<T>> references
+        // Clear the nodes vector to drop all Arc<Mutex<Node<T, E>>> references
         self.nodes.clear();
     }
 }
 
+#[cfg(feature = "serde")]
+mod serde_support {
+    use super::{Graph, LiveNode};
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Serialize};
+
+    /// The on-disk/wire shape of a `Graph`: a compact, reindexed node list,
+    /// `(from, to, weight)` edges referencing positions in it, and the
+    /// `get_or_insert_node` key for each position that has one.
+    #[derive(Serialize, Deserialize)]
+    struct FlatGraph<T, E, K> {
+        nodes: Vec<T>,
+        edges: Vec<(usize, usize, E)>,
+        #[serde(default = "Vec::new")]
+        keys: Vec<(K, usize)>,
+    }
+
+    impl<T, E, K> Serialize for Graph<T, E, K>
+    where
+        T: Serialize + Clone,
+        E: Serialize + Clone,
+        K: Serialize + Clone,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            let live: Vec<LiveNode<T, E>> = self.live_nodes();
+            let nodes = live
+                .iter()
+                .map(|(_, node)| node.lock().unwrap().value.clone())
+                .collect();
+            let mut edges = Vec::new();
+            for (from_id, (_, node)) in live.iter().enumerate() {
+                let node_guard = node.lock().unwrap();
+                let neighbors = node_guard.neighbors.lock().unwrap();
+                for edge in neighbors.iter() {
+                    let Some(target) = edge.target.upgrade() else {
+                        continue;
+                    };
+                    let Some(to_id) = live.iter().position(|(_, arc)| std::sync::Arc::ptr_eq(arc, &target)) else {
+                        continue;
+                    };
+                    edges.push((from_id, to_id, edge.weight.clone()));
+                }
+            }
+            let mut keys = Vec::new();
+            for (key, id) in &self.indices {
+                if let Some(pos) = live.iter().position(|(slot, _)| *slot == id.slot) {
+                    keys.push((key.clone(), pos));
+                }
+            }
+            FlatGraph { nodes, edges, keys }.serialize(serializer)
+        }
+    }
+
+    impl<'de, T, E, K> Deserialize<'de> for Graph<T, E, K>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned,
+        K: DeserializeOwned + Eq + std::hash::Hash,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            let flat = FlatGraph::<T, E, K>::deserialize(deserializer)?;
+            let mut graph = Graph::new();
+            let ids: Vec<_> = flat.nodes.into_iter().map(|value| graph.add_node(value)).collect();
+            for (from_id, to_id, weight) in flat.edges {
+                let from = *ids.get(from_id).ok_or_else(|| {
+                    serde::de::Error::custom(format!("edge references out-of-range node index {from_id}"))
+                })?;
+                let to = *ids.get(to_id).ok_or_else(|| {
+                    serde::de::Error::custom(format!("edge references out-of-range node index {to_id}"))
+                })?;
+                graph
+                    .add_edge_weighted(from, to, weight)
+                    .expect("ids were just produced by this graph, so they are always valid");
+            }
+            for (key, pos) in flat.keys {
+                let &id = ids.get(pos).ok_or_else(|| {
+                    serde::de::Error::custom(format!("key references out-of-range node index {pos}"))
+                })?;
+                graph.indices.insert(key, id);
+            }
+            Ok(graph)
+        }
+    }
+}
+
+/// Error returned by [`Graph::floyd_warshall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloydWarshallError {
+    /// The graph contains a cycle whose total weight is negative, so
+    /// shortest paths are not well-defined.
+    NegativeCycle,
+}
+
+/// A live node's slot index and its underlying node, as returned by
+/// `Graph::live_nodes`.
+type LiveNode<T, E> = (usize, Arc<Mutex<Node<T, E>>>);
+
+/// All-pairs shortest path costs and successor matrix computed by
+/// [`Graph::floyd_warshall`].
+#[derive(Debug)]
+pub struct FloydWarshall<W> {
+    ids: Vec<NodeId>,
+    dist: Vec<Vec<Option<W>>>,
+    next: Vec<Vec<Option<usize>>>,
+}
+
+/// Addition that reports overflow instead of panicking or wrapping, so
+/// [`Graph::floyd_warshall`] can discard an overflowing relaxation candidate
+/// rather than corrupt the distance matrix with it.
+pub trait CheckedAdd: Sized {
+    /// Adds `self` and `rhs`, returning `None` on overflow.
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+}
+
+macro_rules! impl_checked_add {
+    ($($t:ty),* $(,)?) => {
+        $(
+            impl CheckedAdd for $t {
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_add(self, rhs)
+                }
+            }
+        )*
+    };
+}
+
+impl_checked_add!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+/// Adds two optional weights, treating `None` as infinity so `∞ + x` stays
+/// `∞`, and an overflowing `Some + Some` as `∞` too so it never wins a
+/// relaxation.
+fn checked_add<W: Copy + CheckedAdd>(a: Option<W>, b: Option<W>) -> Option<W> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.checked_add(b),
+        _ => None,
+    }
+}
+
+impl<T, W, K> Graph<T, W, K>
+where
+    W: Copy + Ord + Default + CheckedAdd,
+{
+    /// Computes shortest path costs between every pair of live nodes using
+    /// the Floyd-Warshall algorithm.
+    pub fn floyd_warshall(&self) -> Result<FloydWarshall<W>, FloydWarshallError> {
+        let live = self.live_nodes();
+        let n = live.len();
+        let ids: Vec<NodeId> = live
+            .iter()
+            .map(|(slot, _)| NodeId {
+                slot: *slot,
+                generation: self.generations[*slot],
+            })
+            .collect();
+
+        let mut dist = vec![vec![None; n]; n];
+        let mut next = vec![vec![None; n]; n];
+        for (i, _) in live.iter().enumerate() {
+            dist[i][i] = Some(W::default());
+        }
+        for (i, (_, node)) in live.iter().enumerate() {
+            let node_guard = node.lock().unwrap();
+            let neighbors = node_guard.neighbors.lock().unwrap();
+            for edge in neighbors.iter() {
+                let Some(target) = edge.target.upgrade() else {
+                    continue;
+                };
+                let Some(j) = live.iter().position(|(_, arc)| Arc::ptr_eq(arc, &target)) else {
+                    continue;
+                };
+                if dist[i][j].is_none_or(|cur| edge.weight < cur) {
+                    dist[i][j] = Some(edge.weight);
+                    next[i][j] = Some(j);
+                }
+            }
+        }
+
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    let via_k = checked_add(dist[i][k], dist[k][j]);
+                    if let Some(candidate) = via_k {
+                        if dist[i][j].is_none_or(|cur| candidate < cur) {
+                            dist[i][j] = Some(candidate);
+                            next[i][j] = next[i][k];
+                        }
+                    }
+                }
+            }
+        }
+
+        if (0..n).any(|i| dist[i][i].is_some_and(|d| d < W::default())) {
+            return Err(FloydWarshallError::NegativeCycle);
+        }
+
+        Ok(FloydWarshall { ids, dist, next })
+    }
+}
+
+impl<W: Copy> FloydWarshall<W> {
+    fn index_of(&self, id: NodeId) -> Option<usize> {
+        self.ids.iter().position(|&candidate| candidate == id)
+    }
+
+    /// Returns the shortest path cost from `from` to `to`, if one exists.
+    pub fn cost(&self, from: NodeId, to: NodeId) -> Option<W> {
+        let i = self.index_of(from)?;
+        let j = self.index_of(to)?;
+        self.dist[i][j]
+    }
+
+    /// Reconstructs a shortest path from `from` to `to`, if one exists.
+    pub fn path(&self, from: NodeId, to: NodeId) -> Option<Vec<NodeId>> {
+        let i = self.index_of(from)?;
+        let j = self.index_of(to)?;
+        self.dist[i][j]?;
+
+        let mut path = vec![self.ids[i]];
+        let mut current = i;
+        while current != j {
+            current = self.next[current][j]?;
+            path.push(self.ids[current]);
+        }
+        Some(path)
+    }
+}
+
+/// Error returned by [`Graph::from_adjacency_matrix`] for malformed input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjacencyMatrixError {
+    /// A row contained a token other than `0` or `1`.
+    InvalidEntry,
+    /// A row's length didn't match the number of rows.
+    NotSquare,
+}
+
+impl Graph<()> {
+    /// Builds a graph from a textual 0/1 adjacency matrix: one row per
+    /// line, whitespace-separated entries. Row `i`, column `j` being `1`
+    /// adds an edge from node `i` to node `j`; every entry must be `0` or `1`
+    /// and every row must have the same length as the number of rows, or
+    /// this returns an `AdjacencyMatrixError` rather than panicking.
+    pub fn from_adjacency_matrix(s: &str) -> Result<Graph<()>, AdjacencyMatrixError> {
+        let rows: Vec<Vec<u8>> = s
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|entry| match entry {
+                        "0" => Ok(0),
+                        "1" => Ok(1),
+                        _ => Err(AdjacencyMatrixError::InvalidEntry),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+
+        let n = rows.len();
+        if rows.iter().any(|row| row.len() != n) {
+            return Err(AdjacencyMatrixError::NotSquare);
+        }
+
+        let mut graph = Graph::new();
+        let ids: Vec<NodeId> = (0..n).map(|_| graph.add_node(())).collect();
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &entry) in row.iter().enumerate() {
+                if entry == 1 {
+                    graph
+                        .add_edge(ids[i], ids[j])
+                        .expect("ids were just produced by this graph, so they are always valid");
+                }
+            }
+        }
+        Ok(graph)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Graph;
+    use super::{AdjacencyMatrixError, FloydWarshallError, Graph, GraphError, NodeId};
 
     #[test]
     fn test_add_node_and_get_value() {
-        let mut graph = Graph::new();
-        let index = graph.add_node(42);
-        assert_eq!(graph.get_node_value(index), Some(42));
+        let mut graph: Graph<i32> = Graph::new();
+        let id = graph.add_node(42);
+        assert_eq!(graph.get_node_value(id), Ok(42));
     }
 
     #[test]
     fn test_add_edge_and_neighbors() {
-        let mut graph = Graph::new();
+        let mut graph: Graph<&str> = Graph::new();
         let node_a = graph.add_node("A");
         let node_b = graph.add_node("B");
-        graph.add_edge(node_a, node_b);
+        graph.add_edge(node_a, node_b).unwrap();
         let neighbors = graph.neighbors_of(node_a).unwrap();
         assert_eq!(neighbors, vec!["B"]);
     }
 
     #[test]
     fn test_cycle() {
-        let mut graph = Graph::new();
+        let mut graph: Graph<&str> = Graph::new();
         let node_a = graph.add_node("A");
         let node_b = graph.add_node("B");
         let node_c = graph.add_node("C");
 
-        graph.add_edge(node_a, node_b);
-        graph.add_edge(node_b, node_c);
-        graph.add_edge(node_c, node_a); // Creates a cycle
+        graph.add_edge(node_a, node_b).unwrap();
+        graph.add_edge(node_b, node_c).unwrap();
+        graph.add_edge(node_c, node_a).unwrap(); // Creates a cycle
 
         let neighbors_a = graph.neighbors_of(node_a).unwrap();
         assert_eq!(neighbors_a, vec!["B"]);
@@ -122,15 +635,269 @@ mod tests {
     #[test]
     fn test_nonexistent_node() {
         let graph: Graph<i32> = Graph::new();
-        assert_eq!(graph.get_node_value(0), None);
-        assert_eq!(graph.neighbors_of(0), None);
+        let stray = NodeId {
+            slot: 0,
+            generation: 0,
+        };
+        assert_eq!(graph.get_node_value(stray), Err(GraphError::InvalidNode));
+        assert_eq!(graph.neighbors_of(stray), Err(GraphError::InvalidNode));
     }
 
     #[test]
-    #[should_panic(expected = "Node index out of bounds")]
     fn test_add_edge_invalid_indices() {
-        let mut graph = Graph::new();
-        graph.add_node(1);
-        graph.add_edge(0, 1); // There is no node at index 1
+        let mut graph: Graph<i32> = Graph::new();
+        let a = graph.add_node(1);
+        let out_of_bounds = NodeId {
+            slot: 1,
+            generation: 0,
+        };
+        assert_eq!(graph.add_edge(a, out_of_bounds), Err(GraphError::InvalidNode));
+    }
+
+    #[test]
+    fn test_remove_node_rejects_stale_handle() {
+        let mut graph: Graph<&str> = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        graph.add_edge(a, b).unwrap();
+
+        assert_eq!(graph.remove_node(b), Ok("B"));
+        assert_eq!(graph.get_node_value(b), Err(GraphError::NodeDead));
+        // The dangling edge from `a` silently drops the dead neighbor.
+        assert_eq!(graph.neighbors_of(a), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_removed_slot_is_reused_with_new_generation() {
+        let mut graph: Graph<&str> = Graph::new();
+        let a = graph.add_node("A");
+        graph.remove_node(a).unwrap();
+        let c = graph.add_node("C");
+
+        // Same slot, but the stale handle must not resolve to the new node.
+        assert_eq!(graph.get_node_value(a), Err(GraphError::NodeDead));
+        assert_eq!(graph.get_node_value(c), Ok("C"));
+    }
+
+    #[test]
+    fn test_weighted_neighbors() {
+        let mut graph: Graph<&str, i32> = Graph::new();
+        let node_a = graph.add_node("A");
+        let node_b = graph.add_node("B");
+        graph.add_edge_weighted(node_a, node_b, 7).unwrap();
+        let neighbors = graph.weighted_neighbors_of(node_a).unwrap();
+        assert_eq!(neighbors, vec![("B", 7)]);
+    }
+
+    #[test]
+    fn test_unweighted_edge_defaults_to_unit_weight() {
+        let mut graph: Graph<&str> = Graph::new();
+        let node_a = graph.add_node("A");
+        let node_b = graph.add_node("B");
+        graph.add_edge(node_a, node_b).unwrap();
+        assert_eq!(graph.neighbors_of(node_a).unwrap(), vec!["B"]);
+        assert_eq!(
+            graph.weighted_neighbors_of(node_a).unwrap(),
+            vec![("B", ())]
+        );
+    }
+
+    #[test]
+    fn test_floyd_warshall_shortest_paths() {
+        let mut graph: Graph<&str, i32> = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        let c = graph.add_node("C");
+        let d = graph.add_node("D");
+
+        graph.add_edge_weighted(a, b, 1).unwrap();
+        graph.add_edge_weighted(b, c, 2).unwrap();
+        graph.add_edge_weighted(a, c, 10).unwrap();
+        graph.add_edge_weighted(c, d, 1).unwrap();
+
+        let fw = graph.floyd_warshall().unwrap();
+        assert_eq!(fw.cost(a, d), Some(4));
+        assert_eq!(fw.path(a, d), Some(vec![a, b, c, d]));
+        assert_eq!(fw.cost(d, a), None);
+        assert_eq!(fw.cost(a, a), Some(0));
+    }
+
+    #[test]
+    fn test_floyd_warshall_detects_negative_cycle() {
+        let mut graph: Graph<&str, i32> = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        graph.add_edge_weighted(a, b, -5).unwrap();
+        graph.add_edge_weighted(b, a, 1).unwrap();
+
+        assert_eq!(
+            graph.floyd_warshall().unwrap_err(),
+            FloydWarshallError::NegativeCycle
+        );
+    }
+
+    #[test]
+    fn test_floyd_warshall_does_not_overflow_on_large_weights() {
+        let mut graph: Graph<&str, i32> = Graph::new();
+        let a = graph.add_node("A");
+        let b = graph.add_node("B");
+        let c = graph.add_node("C");
+        graph.add_edge_weighted(a, b, i32::MAX - 1).unwrap();
+        graph.add_edge_weighted(b, c, i32::MAX - 1).unwrap();
+
+        // a -> b -> c would overflow i32; it must be discarded, not panic or wrap.
+        let fw = graph.floyd_warshall().unwrap();
+        assert_eq!(fw.cost(a, c), None);
+        assert_eq!(fw.cost(a, b), Some(i32::MAX - 1));
+    }
+
+    #[test]
+    fn test_to_dot_renders_cycle_once() {
+        let mut graph: Graph<&str> = Graph::new();
+        let node_a = graph.add_node("A");
+        let node_b = graph.add_node("B");
+        let node_c = graph.add_node("C");
+
+        graph.add_edge(node_a, node_b).unwrap();
+        graph.add_edge(node_b, node_c).unwrap();
+        graph.add_edge(node_c, node_a).unwrap();
+
+        let dot = graph.to_dot();
+        assert_eq!(
+            dot,
+            "digraph {\n\
+             \x20   0 [label=\"A\"];\n\
+             \x20   1 [label=\"B\"];\n\
+             \x20   2 [label=\"C\"];\n\
+             \x20   0 -> 1;\n\
+             \x20   1 -> 2;\n\
+             \x20   2 -> 0;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_to_dot_skips_removed_nodes() {
+        let mut graph: Graph<&str> = Graph::new();
+        let node_a = graph.add_node("A");
+        let node_b = graph.add_node("B");
+        graph.add_edge(node_a, node_b).unwrap();
+        graph.remove_node(node_b).unwrap();
+
+        let dot = graph.to_dot();
+        assert_eq!(dot, "digraph {\n    0 [label=\"A\"];\n}\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trips_a_cycle() {
+        let mut graph: Graph<String, i32> = Graph::new();
+        let node_a = graph.add_node("A".to_string());
+        let node_b = graph.add_node("B".to_string());
+        let node_c = graph.add_node("C".to_string());
+        graph.add_edge_weighted(node_a, node_b, 1).unwrap();
+        graph.add_edge_weighted(node_b, node_c, 2).unwrap();
+        graph.add_edge_weighted(node_c, node_a, 3).unwrap();
+
+        let json = serde_json::to_string(&graph).unwrap();
+        let restored: Graph<String, i32> = serde_json::from_str(&json).unwrap();
+
+        let ids: Vec<NodeId> = (0..3)
+            .map(|slot| NodeId { slot, generation: 0 })
+            .collect();
+        assert_eq!(
+            restored.weighted_neighbors_of(ids[0]).unwrap(),
+            vec![("B".to_string(), 1)]
+        );
+        assert_eq!(
+            restored.weighted_neighbors_of(ids[1]).unwrap(),
+            vec![("C".to_string(), 2)]
+        );
+        assert_eq!(
+            restored.weighted_neighbors_of(ids[2]).unwrap(),
+            vec![("A".to_string(), 3)]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trips_keyed_nodes() {
+        let mut graph: Graph<String, (), String> = Graph::new();
+        let a = graph.get_or_insert_node("a".to_string(), "A".to_string());
+        let b = graph.get_or_insert_node("b".to_string(), "B".to_string());
+
+        let json = serde_json::to_string(&graph).unwrap();
+        let restored: Graph<String, (), String> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.node_for_key(&"a".to_string()), Some(a));
+        assert_eq!(restored.node_for_key(&"b".to_string()), Some(b));
+        assert_eq!(restored.node_for_key(&"c".to_string()), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_rejects_out_of_range_edge_index() {
+        let json = r#"{"nodes":["A","B"],"edges":[[0,5,1]]}"#;
+        let result: Result<Graph<String, i32>, _> = serde_json::from_str(json);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_from_adjacency_matrix() {
+        let graph = Graph::from_adjacency_matrix(
+            "0 1 0\n\
+             0 0 1\n\
+             1 0 0\n",
+        )
+        .unwrap();
+        let ids: Vec<NodeId> = (0..3)
+            .map(|slot| NodeId { slot, generation: 0 })
+            .collect();
+        assert_eq!(graph.neighbors_of(ids[0]).unwrap(), vec![()]);
+        assert_eq!(graph.neighbors_of(ids[1]).unwrap(), vec![()]);
+        assert_eq!(graph.neighbors_of(ids[2]).unwrap(), vec![()]);
+    }
+
+    #[test]
+    fn test_from_adjacency_matrix_rejects_non_square_input() {
+        let Err(err) = Graph::from_adjacency_matrix("0 1\n1 1 0\n") else {
+            panic!("expected an error");
+        };
+        assert_eq!(err, AdjacencyMatrixError::NotSquare);
+    }
+
+    #[test]
+    fn test_from_adjacency_matrix_rejects_invalid_entry() {
+        let Err(err) = Graph::from_adjacency_matrix("0 2\n1 0\n") else {
+            panic!("expected an error");
+        };
+        assert_eq!(err, AdjacencyMatrixError::InvalidEntry);
+    }
+
+    #[test]
+    fn test_remove_node_clears_its_key_from_the_index() {
+        let mut graph: Graph<&str, (), &str> = Graph::new();
+        let a = graph.get_or_insert_node("a", "A");
+        graph.remove_node(a).unwrap();
+
+        // The key must not keep resolving to the now-dead handle.
+        assert_eq!(graph.node_for_key(&"a"), None);
+        let a_again = graph.get_or_insert_node("a", "A again");
+        assert_ne!(a, a_again);
+        assert_eq!(graph.get_node_value(a_again), Ok("A again"));
+    }
+
+    #[test]
+    fn test_get_or_insert_node_reuses_existing_key() {
+        let mut graph: Graph<&str, (), &str> = Graph::new();
+        let a = graph.get_or_insert_node("a", "A");
+        let a_again = graph.get_or_insert_node("a", "ignored");
+        let b = graph.get_or_insert_node("b", "B");
+
+        assert_eq!(a, a_again);
+        assert_ne!(a, b);
+        assert_eq!(graph.get_node_value(a), Ok("A"));
+        assert_eq!(graph.node_for_key(&"a"), Some(a));
+        assert_eq!(graph.node_for_key(&"z"), None);
     }
 }